@@ -1,10 +1,37 @@
+use clap::{Parser, ValueEnum};
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
 use std::{
     fmt,
     fs::File,
     io::{self, BufRead},
-    path::Path,
+    net::Ipv6Addr,
+    path::PathBuf,
+    str::FromStr,
 };
 
+/// Anything that can go wrong while parsing a prefix from text.
+#[derive(Debug)]
+enum ParseError {
+    MalformedLine,
+    BadAddress,
+    BadMask,
+    HostBitsSet,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::MalformedLine => write!(f, "malformed line"),
+            ParseError::BadAddress => write!(f, "invalid address"),
+            ParseError::BadMask => write!(f, "invalid mask"),
+            ParseError::HostBitsSet => write!(f, "host bits set outside the network mask"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 #[repr(transparent)]
 #[derive(Clone, Copy)]
 /// The CIDR notation is stored instead of the actual bitmask
@@ -39,11 +66,11 @@ fn parse_octets(s: &str) -> Option<u32> {
 }
 
 impl IPv4Mask {
-    fn parse(s: &str) -> Option<IPv4Mask> {
+    fn parse(s: &str) -> Result<IPv4Mask, ParseError> {
         let netid_bits = if s.contains('.') {
             let mask = match parse_octets(s) {
                 Some(x) => x,
-                None => return None,
+                None => return Err(ParseError::BadMask),
             };
 
             // get how many bits long the host id is
@@ -60,7 +87,7 @@ impl IPv4Mask {
 
             // check if the mask is valid
             if u32::MAX << host_bits != mask {
-                return None;
+                return Err(ParseError::BadMask);
             }
 
             32 - host_bits
@@ -68,14 +95,14 @@ impl IPv4Mask {
             match s.parse() {
                 Ok(x) => {
                     if x > 32 {
-                        return None;
+                        return Err(ParseError::BadMask);
                     }
                     x
                 }
-                Err(e) => panic!("{}", e),
+                Err(_) => return Err(ParseError::BadMask),
             }
         };
-        Some(IPv4Mask(netid_bits))
+        Ok(IPv4Mask(netid_bits))
     }
 
     fn netid_mask(&self) -> u32 {
@@ -100,13 +127,13 @@ impl fmt::Display for IPv4Mask {
 struct IPv4Address(u32);
 
 impl IPv4Address {
-    fn parse(s: &str) -> Option<IPv4Address> {
+    fn parse(s: &str) -> Result<IPv4Address, ParseError> {
         let addr = match parse_octets(s) {
             Some(x) => x,
-            None => return None,
+            None => return Err(ParseError::BadAddress),
         };
 
-        Some(IPv4Address(addr))
+        Ok(IPv4Address(addr))
     }
 }
 
@@ -121,55 +148,453 @@ impl fmt::Display for IPv4Address {
     }
 }
 
-fn create_summary_route(pairs: Vec<(IPv4Address, IPv4Mask)>) -> (IPv4Address, IPv4Mask) {
-    let mut common_network_part_bits = 0;
-    'outer: loop {
-        for pair in pairs.iter() {
-            let bit = 1 << (31 - common_network_part_bits);
-            let ip = pair.0;
-            if ip.0 & bit != pairs[0].0 .0 & bit {
-                break 'outer;
+/// The backing integer of an address family, abstracted so the summarization
+/// logic can run over either a 32-bit (IPv4) or 128-bit (IPv6) address.
+///
+/// `prefixlen` is always counted from the most significant bit.
+trait BitAddr: Copy + Ord {
+    /// Total number of bits in the address (32 for IPv4, 128 for IPv6).
+    fn bit_width() -> usize;
+
+    /// Clear every host bit, keeping only the leading `prefixlen` bits.
+    fn masked(self, prefixlen: usize) -> Self;
+
+    /// Length of the longest prefix shared by every value in `values`.
+    fn common_prefix(values: &[Self]) -> usize {
+        let width = Self::bit_width();
+        let first = values[0];
+        let mut len = 0;
+        while len < width {
+            let next = len + 1;
+            if values.iter().any(|v| v.masked(next) != first.masked(next)) {
+                break;
             }
+            len = next;
         }
-        common_network_part_bits += 1;
+        len
     }
+}
 
-    let common_mask = IPv4Mask(common_network_part_bits);
-    let common_ip = IPv4Address(pairs[0].0 .0 & common_mask.netid_mask());
+impl BitAddr for u32 {
+    fn bit_width() -> usize {
+        32
+    }
 
-    (common_ip, common_mask)
+    fn masked(self, prefixlen: usize) -> u32 {
+        if prefixlen == 0 {
+            0
+        } else if prefixlen >= 32 {
+            self
+        } else {
+            self & (u32::MAX << (32 - prefixlen))
+        }
+    }
 }
 
-fn main() {
-    let path = Path::new("test.txt");
-    let display = path.display();
+impl BitAddr for u128 {
+    fn bit_width() -> usize {
+        128
+    }
+
+    fn masked(self, prefixlen: usize) -> u128 {
+        if prefixlen == 0 {
+            0
+        } else if prefixlen >= 128 {
+            self
+        } else {
+            self & (u128::MAX << (128 - prefixlen))
+        }
+    }
+}
+
+/// Collapse every input into the single common supernet that covers them all.
+///
+/// This is the over-broad summary: disjoint inputs still yield one prefix, so
+/// `10.0.0.0/8` plus `192.168.0.0/16` becomes `0.0.0.0/0`. Use
+/// [`aggregate_prefixes`] for an exact minimal cover.
+fn create_summary_route<T: BitAddr>(prefixes: &[(T, usize)]) -> (T, usize) {
+    let nets: Vec<T> = prefixes.iter().map(|(addr, _)| *addr).collect();
+    // clamp to the shortest input prefix so the supernet still covers every
+    // input's full address range, not just the shared network bits
+    let min_len = prefixes.iter().map(|(_, len)| *len).min().unwrap();
+    let len = T::common_prefix(&nets).min(min_len);
+    (nets[0].masked(len), len)
+}
+
+/// Aggregate the input prefixes into the fewest prefixes that exactly cover
+/// them, without introducing any address space that wasn't already present.
+///
+/// Unlike [`create_summary_route`], which collapses everything into a single
+/// common supernet, this keeps disjoint blocks separate and only merges
+/// prefixes that are truly adjacent.
+fn aggregate_prefixes<T: BitAddr>(prefixes: Vec<(T, usize)>) -> Vec<(T, usize)> {
+    // normalize every entry to its masked network address and prefix length
+    let mut normalized: Vec<(T, usize)> = prefixes
+        .iter()
+        .map(|(addr, prefixlen)| (addr.masked(*prefixlen), *prefixlen))
+        .collect();
+
+    // sort ascending by (network, prefix length)
+    normalized.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
+
+    // drop any prefix fully contained in an earlier (shorter-or-equal) one
+    let mut deduped: Vec<(T, usize)> = Vec::new();
+    for (addr, prefixlen) in normalized {
+        let contained = deduped
+            .iter()
+            .any(|(outer_addr, outer_len)| *outer_len <= prefixlen && addr.masked(*outer_len) == *outer_addr);
+        if !contained {
+            deduped.push((addr, prefixlen));
+        }
+    }
+
+    // repeatedly merge sibling pairs until a full pass changes nothing
+    loop {
+        let mut merged: Vec<(T, usize)> = Vec::with_capacity(deduped.len());
+        let mut changed = false;
+        let mut i = 0;
+        while i < deduped.len() {
+            if i + 1 < deduped.len() {
+                let (a_addr, a_len) = deduped[i];
+                let (b_addr, b_len) = deduped[i + 1];
+                // siblings: identical length p sharing the same upper p-1 bits.
+                // After dedup the two networks are distinct, so equal parents
+                // means they differ only in bit 32-p (or 128-p).
+                if a_len == b_len && a_len > 0 {
+                    let parent = a_len - 1;
+                    if a_addr.masked(parent) == b_addr.masked(parent) {
+                        merged.push((a_addr.masked(parent), parent));
+                        changed = true;
+                        i += 2;
+                        continue;
+                    }
+                }
+            }
+            merged.push(deduped[i]);
+            i += 1;
+        }
+        deduped = merged;
+        if !changed {
+            break;
+        }
+    }
+
+    deduped.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.cmp(&b.1)));
+    deduped
+}
+
+#[derive(Clone, Copy)]
+struct IPv6Address(u128);
+
+impl IPv6Address {
+    fn parse(s: &str) -> Result<IPv6Address, ParseError> {
+        match s.parse::<Ipv6Addr>() {
+            Ok(addr) => Ok(IPv6Address(u128::from(addr))),
+            Err(_) => Err(ParseError::BadAddress),
+        }
+    }
+}
+
+impl fmt::Display for IPv6Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Ipv6Addr::from(self.0))
+    }
+}
+
+/// Like [`IPv4Mask`], but a prefix length of up to 128 bits for IPv6.
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+struct IPv6Mask(usize);
+
+impl IPv6Mask {
+    fn parse(s: &str) -> Result<IPv6Mask, ParseError> {
+        match s.parse::<usize>() {
+            Ok(x) if x <= 128 => Ok(IPv6Mask(x)),
+            _ => Err(ParseError::BadMask),
+        }
+    }
+}
+
+impl fmt::Display for IPv6Mask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A network prefix of either address family, so a single input file can mix
+/// IPv4 and IPv6 entries.
+enum IpNet {
+    V4(IPv4Address, IPv4Mask),
+    V6(IPv6Address, IPv6Mask),
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpNet::V4(addr, mask) => write!(f, "{}/{}", addr, mask),
+            IpNet::V6(addr, mask) => write!(f, "{}/{}", addr, mask),
+        }
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<IpNet, ParseError> {
+        // an IPv6 literal is the only input form that can contain a colon
+        if s.contains(':') {
+            let (addr_str, mask_str) = split_prefix(s)?;
+            let addr = IPv6Address::parse(addr_str)?;
+            let mask = IPv6Mask::parse(mask_str)?;
+            if addr.0 != addr.0.masked(mask.0) {
+                return Err(ParseError::HostBitsSet);
+            }
+            Ok(IpNet::V6(addr, mask))
+        } else {
+            let (addr, mask) = parse_prefix(s)?;
+            Ok(IpNet::V4(addr, mask))
+        }
+    }
+}
+
+impl Serialize for IPv4Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl Serialize for IPv6Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl Serialize for IPv4Mask {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0 as u64)
+    }
+}
+
+impl Serialize for IPv6Mask {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0 as u64)
+    }
+}
+
+impl Serialize for IpNet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut st = serializer.serialize_struct("Prefix", 3)?;
+        match self {
+            IpNet::V4(addr, mask) => {
+                st.serialize_field("network", addr)?;
+                st.serialize_field("prefixlen", mask)?;
+                st.serialize_field("netmask", &IPv4Address(u32::MAX.masked(mask.0)))?;
+            }
+            IpNet::V6(addr, mask) => {
+                st.serialize_field("network", addr)?;
+                st.serialize_field("prefixlen", mask)?;
+                st.serialize_field("netmask", &IPv6Address(u128::MAX.masked(mask.0)))?;
+            }
+        }
+        st.end()
+    }
+}
+
+/// How summarized prefixes are rendered to stdout.
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    /// The original `addr/len` plaintext, one prefix per line.
+    Text,
+    /// A JSON array of `{network, prefixlen, netmask}` objects.
+    Json,
+    /// Comma-separated rows with a `network,prefixlen,netmask` header.
+    Csv,
+}
+
+fn emit(nets: &[IpNet], format: OutputFormat) {
+    match format {
+        OutputFormat::Text => {
+            for net in nets {
+                println!("{}", net);
+            }
+        }
+        OutputFormat::Json => match serde_json::to_string_pretty(nets) {
+            Ok(s) => println!("{}", s),
+            Err(e) => eprintln!("error: {}", e),
+        },
+        OutputFormat::Csv => {
+            println!("network,prefixlen,netmask");
+            for net in nets {
+                let (network, prefixlen, netmask) = match net {
+                    IpNet::V4(addr, mask) => (
+                        addr.to_string(),
+                        mask.0,
+                        IPv4Address(u32::MAX.masked(mask.0)).to_string(),
+                    ),
+                    IpNet::V6(addr, mask) => (
+                        addr.to_string(),
+                        mask.0,
+                        IPv6Address(u128::MAX.masked(mask.0)).to_string(),
+                    ),
+                };
+                println!("{},{},{}", network, prefixlen, netmask);
+            }
+        }
+    }
+}
 
-    let file = match File::open(&path) {
-        Ok(f) => f,
-        Err(e) => panic!("can't open file {}: {}", display, e),
+/// Split a line into its address and mask halves on the first `/` or, failing
+/// that, the first run of whitespace. Both halves are returned trimmed.
+fn split_prefix(line: &str) -> Result<(&str, &str), ParseError> {
+    let (addr_str, mask_str) = if let Some(idx) = line.find('/') {
+        (&line[..idx], &line[idx + 1..])
+    } else if let Some(idx) = line.find(char::is_whitespace) {
+        (&line[..idx], &line[idx + 1..])
+    } else {
+        return Err(ParseError::MalformedLine);
     };
 
-    let lines = io::BufReader::new(file).lines();
-    let mut pairs: Vec<(IPv4Address, IPv4Mask)> = Vec::new();
+    Ok((addr_str.trim(), mask_str.trim()))
+}
+
+/// Parse a single prefix written in any of the three accepted syntaxes:
+///
+/// * CIDR length: `192.0.2.16/29`
+/// * slash-delimited dotted mask: `192.0.2.16/255.255.255.248`
+/// * space-delimited dotted mask: `192.0.2.16 255.255.255.248`
+///
+/// The address/mask split is detected from the delimiter; the mask form is
+/// then left to [`IPv4Mask::parse`], which already distinguishes a dotted
+/// mask from a plain CIDR length.
+fn parse_prefix(line: &str) -> Result<(IPv4Address, IPv4Mask), ParseError> {
+    let (addr_str, mask_str) = split_prefix(line)?;
 
-    for line in lines {
-        if let Ok(l) = line {
-            let parts: Vec<&str> = l.split('/').collect();
-            if parts.len() != 2 {
-                panic!("invalid line");
+    let addr = IPv4Address::parse(addr_str)?;
+    let mask = IPv4Mask::parse(mask_str)?;
+    if addr.0 != addr.0 & mask.netid_mask() {
+        return Err(ParseError::HostBitsSet);
+    }
+
+    Ok((addr, mask))
+}
+
+/// Read prefixes from `reader`, one per line.
+///
+/// In strict mode the first malformed line aborts parsing and its error is
+/// returned. In lenient mode the offending line is reported to stderr along
+/// with its line number and skipped, so parsing continues with whatever valid
+/// prefixes remain.
+fn read_prefixes<R: BufRead>(reader: R, strict: bool) -> Result<Vec<IpNet>, ParseError> {
+    let mut nets: Vec<IpNet> = Vec::new();
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line_no = idx + 1;
+        let l = match line {
+            Ok(l) => l,
+            Err(_) => continue,
+        };
+        if l.trim().is_empty() {
+            continue;
+        }
+
+        match l.parse::<IpNet>() {
+            Ok(net) => nets.push(net),
+            Err(e) => {
+                if strict {
+                    return Err(e);
+                }
+                eprintln!("line {}: {}, skipping", line_no, e);
             }
+        }
+    }
+
+    Ok(nets)
+}
+
+/// Summarize IPv4/IPv6 route prefixes read from files or stdin.
+#[derive(Parser)]
+#[command(long_about = None)]
+struct Args {
+    /// Input files to read prefixes from; reads stdin when none are given.
+    files: Vec<PathBuf>,
+
+    /// Stop at the first malformed line instead of reporting and skipping it.
+    #[arg(long)]
+    strict: bool,
 
-            let addr = IPv4Address::parse(parts[0].trim()).unwrap();
-            let mask = IPv4Mask::parse(parts[1].trim()).unwrap();
-            if addr.0 != addr.0 & mask.netid_mask() {
-                panic!("invalid mask: ip: {} mask: {}", addr, mask);
+    /// Collapse each family into a single common supernet instead of the
+    /// minimal exact cover.
+    #[arg(long)]
+    single: bool,
+
+    /// Output format for the summarized prefixes.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let nets = if args.files.is_empty() {
+        let stdin = io::stdin();
+        read_prefixes(stdin.lock(), args.strict)
+    } else {
+        let mut all: Vec<IpNet> = Vec::new();
+        for path in &args.files {
+            let file = match File::open(path) {
+                Ok(f) => f,
+                Err(e) => {
+                    eprintln!("can't open file {}: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            };
+            match read_prefixes(io::BufReader::new(file), args.strict) {
+                Ok(mut nets) => all.append(&mut nets),
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
             }
+        }
+        Ok(all)
+    };
 
-            println!("{}/{}", addr, mask);
-            pairs.push((addr, mask));
+    let nets = match nets {
+        Ok(nets) => nets,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // summarization happens independently per address family
+    let mut v4: Vec<(u32, usize)> = Vec::new();
+    let mut v6: Vec<(u128, usize)> = Vec::new();
+    for net in nets {
+        match net {
+            IpNet::V4(addr, mask) => v4.push((addr.0, mask.0)),
+            IpNet::V6(addr, mask) => v6.push((addr.0, mask.0)),
+        }
+    }
+
+    let mut results: Vec<IpNet> = Vec::new();
+    if args.single {
+        if !v4.is_empty() {
+            let (addr, prefixlen) = create_summary_route(&v4);
+            results.push(IpNet::V4(IPv4Address(addr), IPv4Mask(prefixlen)));
+        }
+        if !v6.is_empty() {
+            let (addr, prefixlen) = create_summary_route(&v6);
+            results.push(IpNet::V6(IPv6Address(addr), IPv6Mask(prefixlen)));
+        }
+    } else {
+        for (addr, prefixlen) in aggregate_prefixes(v4) {
+            results.push(IpNet::V4(IPv4Address(addr), IPv4Mask(prefixlen)));
+        }
+        for (addr, prefixlen) in aggregate_prefixes(v6) {
+            results.push(IpNet::V6(IPv6Address(addr), IPv6Mask(prefixlen)));
         }
     }
 
-    let summary = create_summary_route(pairs);
-    println!("summary: {} {}", summary.0, summary.1);
+    emit(&results, args.format);
 }